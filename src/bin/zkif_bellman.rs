@@ -0,0 +1,72 @@
+//! stdin/stdout front-end for the zkInterface bellman backend.
+//!
+//! Usage: `zkif_bellman <prove|verify> [proof_path] [messages_path]`
+//!
+//! Concatenated zkInterface messages are read from `messages_path` if given,
+//! or from stdin otherwise. `proof_path` names the directory where the
+//! generated `key`/`proof` are read from and written to (default: `local`).
+
+use std::env;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::process::exit;
+
+use algebra::curves::bls12_381::Bls12;
+use zkinterface::reading::Messages;
+use zkinterface_bellman::zkif_backend::{zkif_backend, Mode};
+
+const DEFAULT_OUT_DIR: &str = "local";
+
+fn read_messages(path: Option<&String>) -> Messages {
+    let mut buffer = Vec::new();
+
+    match path {
+        Some(path) => {
+            File::open(path)
+                .and_then(|mut f| f.read_to_end(&mut buffer))
+                .unwrap_or_else(|e| panic!("failed to read {}: {}", path, e));
+        }
+        None => {
+            io::stdin()
+                .read_to_end(&mut buffer)
+                .expect("failed to read zkInterface messages from stdin");
+        }
+    }
+
+    let mut messages = Messages::new(1);
+    messages
+        .read_buffer(&buffer)
+        .expect("failed to parse zkInterface messages");
+    messages
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    if args.len() < 2 {
+        eprintln!("Usage: {} <prove|verify> [proof_path] [messages_path]", args[0]);
+        exit(2);
+    }
+
+    let command = args[1].as_str();
+    let out_dir = PathBuf::from(args.get(2).map(String::as_str).unwrap_or(DEFAULT_OUT_DIR));
+    let messages = read_messages(args.get(3));
+
+    let mode = match command {
+        "prove" => Mode::Prove,
+        "verify" => Mode::Verify,
+        other => {
+            eprintln!("Unknown command: {}", other);
+            exit(2);
+        }
+    };
+
+    match zkif_backend::<Bls12>(&messages, &out_dir, mode) {
+        Ok(()) => exit(0),
+        Err(e) => {
+            eprintln!("{} failed: {:?}", command, e);
+            exit(1);
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use r1cs_core::{
+    ConstraintSynthesizer,
+    ConstraintSystem,
+    Index,
+    LinearCombination,
+    SynthesisError,
+    Variable,
+};
+use algebra::PrimeField;
+use std::io::Write;
+use super::import::{fr_to_le, prime_field_maximum};
+use zkinterface::writing::{CircuitOwned, ConstraintOwned, ConstraintSystemOwned, VariablesOwned, WitnessOwned};
+
+
+/// A `ConstraintSystem` that records a native bellman circuit's constraints
+/// and assignments instead of checking them, so that it can be serialized
+/// out as zkInterface messages.
+///
+/// This mirrors the collecting constraint system pattern: each constraint's
+/// `a`/`b`/`c` linear combinations are kept as `(coefficient, Index)` term
+/// vectors, and variable assignments are recorded in allocation order.
+pub struct ExportingConstraintSystem<F: PrimeField> {
+    constraints: Vec<(Vec<(F, Index)>, Vec<(F, Index)>, Vec<(F, Index)>)>,
+    public_values: Vec<F>,
+    private_values: Vec<F>,
+}
+
+impl<F: PrimeField> ExportingConstraintSystem<F> {
+    pub fn new() -> Self {
+        ExportingConstraintSystem {
+            constraints: vec![],
+            public_values: vec![],
+            private_values: vec![],
+        }
+    }
+
+    /// Run a native bellman circuit, collecting its constraints and assignments.
+    pub fn collect<C: ConstraintSynthesizer<F>>(circuit: C) -> Result<Self, SynthesisError> {
+        let mut cs = Self::new();
+        circuit.generate_constraints(&mut cs)?;
+        Ok(cs)
+    }
+
+    /// Map a bellman `Index` to the 0/public/private id ranges used by the
+    /// zkInterface reader (see `ZKIFCircuit` in `zkif_backend`): `0` is the
+    /// constant one, `1..=num_public` are the connection variables, and the
+    /// remaining ids are private variables.
+    fn var_id(&self, index: Index) -> u64 {
+        match index {
+            Index::Input(0) => 0,
+            Index::Input(i) => i as u64,
+            Index::Aux(i) => self.public_values.len() as u64 + 1 + i as u64,
+        }
+    }
+
+    fn terms_owned(&self, terms: &[(F, Index)]) -> VariablesOwned {
+        VariablesOwned {
+            variable_ids: terms.iter().map(|(_, index)| self.var_id(*index)).collect(),
+            values: Some(terms.iter().flat_map(|(coeff, _)| fr_to_le(*coeff)).collect()),
+        }
+    }
+
+    /// Emit the collected circuit as `Circuit`, `R1CSConstraints`, and
+    /// `Witness` zkInterface messages.
+    pub fn write_to<W: Write>(&self, mut w: W) -> Result<(), SynthesisError> {
+        let num_public = self.public_values.len() as u64;
+        let free_variable_id = num_public + 1 + self.private_values.len() as u64;
+
+        let circuit = CircuitOwned {
+            connections: VariablesOwned {
+                variable_ids: (1..=num_public).collect(),
+                values: Some(self.public_values.iter().flat_map(|v| fr_to_le(*v)).collect()),
+            },
+            free_variable_id,
+            r1cs_generation: true,
+            field_maximum: Some(prime_field_maximum::<F>()),
+        };
+        circuit.write(&mut w)?;
+
+        let r1cs = ConstraintSystemOwned {
+            constraints: self.constraints.iter().map(|(a, b, c)| ConstraintOwned {
+                a: self.terms_owned(a),
+                b: self.terms_owned(b),
+                c: self.terms_owned(c),
+            }).collect(),
+        };
+        r1cs.write(&mut w)?;
+
+        let witness = WitnessOwned {
+            assigned_variables: VariablesOwned {
+                variable_ids: (num_public + 1..free_variable_id).collect(),
+                values: Some(self.private_values.iter().flat_map(|v| fr_to_le(*v)).collect()),
+            },
+        };
+        witness.write(&mut w)?;
+
+        Ok(())
+    }
+}
+
+impl<F: PrimeField> ConstraintSystem<F> for ExportingConstraintSystem<F> {
+    type Root = Self;
+
+    fn alloc<FN, A, AR>(&mut self, _annotation: A, f: FN) -> Result<Variable, SynthesisError>
+        where FN: FnOnce() -> Result<F, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let value = f()?;
+        let index = self.private_values.len();
+        self.private_values.push(value);
+        Ok(Variable::new_unchecked(Index::Aux(index)))
+    }
+
+    fn alloc_input<FN, A, AR>(&mut self, _annotation: A, f: FN) -> Result<Variable, SynthesisError>
+        where FN: FnOnce() -> Result<F, SynthesisError>, A: FnOnce() -> AR, AR: Into<String>
+    {
+        let value = f()?;
+        // Index::Input(0) is reserved for the constant one.
+        let index = self.public_values.len() + 1;
+        self.public_values.push(value);
+        Ok(Variable::new_unchecked(Index::Input(index)))
+    }
+
+    fn enforce<A, AR, LA, LB, LC>(&mut self, _annotation: A, a: LA, b: LB, c: LC)
+        where A: FnOnce() -> AR,
+              AR: Into<String>,
+              LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+              LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+              LC: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+    {
+        let a = a(LinearCombination::zero());
+        let b = b(LinearCombination::zero());
+        let c = c(LinearCombination::zero());
+
+        self.constraints.push((
+            a.as_ref().iter().map(|(var, coeff)| (*coeff, var.get_unchecked())).collect(),
+            b.as_ref().iter().map(|(var, coeff)| (*coeff, var.get_unchecked())).collect(),
+            c.as_ref().iter().map(|(var, coeff)| (*coeff, var.get_unchecked())).collect(),
+        ));
+    }
+
+    fn push_namespace<NR, N>(&mut self, _name_fn: N) where NR: Into<String>, N: FnOnce() -> NR {}
+
+    fn pop_namespace(&mut self) {}
+
+    fn get_root(&mut self) -> &mut Self::Root {
+        self
+    }
+}
+
+
+#[test]
+fn test_export_then_import() {
+    use r1cs_std::circuit::num::AllocatedNum;
+    use algebra_bls12_381::Bls12_381Fr as Fr;
+    use super::zkif_backend::ZKIFCircuit;
+    use zkinterface::reading::Messages;
+
+    /// `a * b = c`, with `a` and `b` private and `c` public.
+    struct Multiply {
+        a: Option<Fr>,
+        b: Option<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for Multiply {
+        fn generate_constraints<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let a = AllocatedNum::alloc(cs.ns(|| "a"), || self.a.ok_or(SynthesisError::AssignmentMissing))?;
+            let b = AllocatedNum::alloc(cs.ns(|| "b"), || self.b.ok_or(SynthesisError::AssignmentMissing))?;
+            let c = AllocatedNum::alloc_input(cs.ns(|| "c"), || {
+                let mut c = self.a.ok_or(SynthesisError::AssignmentMissing)?;
+                c.mul_assign(&self.b.ok_or(SynthesisError::AssignmentMissing)?);
+                Ok(c)
+            })?;
+
+            cs.enforce(
+                || "a * b = c",
+                |lc| lc + a.get_variable(),
+                |lc| lc + b.get_variable(),
+                |lc| lc + c.get_variable(),
+            );
+
+            Ok(())
+        }
+    }
+
+    let circuit = Multiply { a: Some(Fr::from(3u64)), b: Some(Fr::from(5u64)) };
+
+    let exported = ExportingConstraintSystem::collect(circuit).unwrap();
+
+    let mut buffer = vec![];
+    exported.write_to(&mut buffer).unwrap();
+
+    let mut messages = Messages::new(1);
+    messages.read_buffer(&buffer).unwrap();
+
+    // Re-import the exported messages and make sure the round-tripped
+    // circuit still enforces its single constraint without error.
+    struct NullCS;
+    impl<F: PrimeField> ConstraintSystem<F> for NullCS {
+        type Root = Self;
+        fn alloc<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+            where FN: FnOnce() -> Result<F, SynthesisError>, A: FnOnce() -> AR, AR: Into<String> { f()?; Ok(Variable::new_unchecked(Index::Aux(0))) }
+        fn alloc_input<FN, A, AR>(&mut self, _: A, f: FN) -> Result<Variable, SynthesisError>
+            where FN: FnOnce() -> Result<F, SynthesisError>, A: FnOnce() -> AR, AR: Into<String> { f()?; Ok(Variable::new_unchecked(Index::Input(0))) }
+        fn enforce<A, AR, LA, LB, LC>(&mut self, _: A, _: LA, _: LB, _: LC)
+            where A: FnOnce() -> AR, AR: Into<String>,
+                  LA: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+                  LB: FnOnce(LinearCombination<F>) -> LinearCombination<F>,
+                  LC: FnOnce(LinearCombination<F>) -> LinearCombination<F> {}
+        fn push_namespace<NR, N>(&mut self, _: N) where NR: Into<String>, N: FnOnce() -> NR {}
+        fn pop_namespace(&mut self) {}
+        fn get_root(&mut self) -> &mut Self::Root { self }
+    }
+
+    let reimported = ZKIFCircuit { messages: &messages };
+    reimported.generate_constraints(&mut NullCS).unwrap();
+}
@@ -5,7 +5,7 @@ use r1cs_core::{
     Variable,
     ConstraintVar,
 };
-use algebra::{FromBytes, PrimeField, BigInteger};
+use algebra::{FromBytes, PrimeField, BigInteger, ToBytes};
 use r1cs_std::fields::{FieldGadget, fp::FpGadget};
 use r1cs_std::prelude::*;
 use std::collections::HashMap;
@@ -28,6 +28,20 @@ pub fn le_to_fr<F: PrimeField>(bytes_le: &[u8]) -> F {
     F::from_repr(repr)
 }
 
+/// Convert a bellman Fr to zkInterface little-endian bytes (inverse of `le_to_fr`).
+pub fn fr_to_le<F: PrimeField>(f: F) -> Vec<u8> {
+    let mut bytes_le = Vec::new();
+    f.into_repr().write(&mut bytes_le).unwrap();
+    bytes_le
+}
+
+/// The zkInterface `field_maximum` value for `F`: the modulus minus one,
+/// serialized like any other field element. Used to detect a zkif circuit
+/// that was authored for a different field than the one a backend targets.
+pub fn prime_field_maximum<F: PrimeField>() -> Vec<u8> {
+    fr_to_le(F::zero() - F::one())
+}
+
 /// Convert zkInterface terms to bellman LinearCombination.
 pub fn terms_to_lc<F: PrimeField>(vars: &HashMap<u64, Variable>, terms: &[Term]) -> LinearCombination<F> {
     let mut lc = LinearCombination::zero();
@@ -39,6 +53,48 @@ pub fn terms_to_lc<F: PrimeField>(vars: &HashMap<u64, Variable>, terms: &[Term])
     lc
 }
 
+/// Evaluate every imported R1CS constraint against the witness carried by
+/// `messages`, without running the prover. This is a cheap correctness pass
+/// that integrators can run before paying for parameter generation and
+/// proving: on the first unsatisfied constraint, returns its index and its
+/// evaluated `(a, b, c)` values.
+pub fn check_witness<F: PrimeField>(messages: &Messages) -> Result<(), (usize, F, F, F)> {
+    let mut values = HashMap::<u64, F>::new();
+    values.insert(0, F::one());
+
+    if let Some(public_vars) = messages.connection_variables() {
+        for var in public_vars {
+            values.insert(var.id, le_to_fr::<F>(var.value));
+        }
+    }
+
+    if let Some(private_vars) = messages.private_variables() {
+        for var in private_vars {
+            values.insert(var.id, le_to_fr::<F>(var.value));
+        }
+    }
+
+    let eval = |terms: &[Term]| -> F {
+        terms.iter().fold(F::zero(), |acc, term| {
+            let coeff = le_to_fr::<F>(term.value);
+            let val = *values.get(&term.id).unwrap();
+            acc + &(coeff * &val)
+        })
+    };
+
+    for (i, constraint) in messages.iter_constraints().enumerate() {
+        let a = eval(&constraint.a);
+        let b = eval(&constraint.b);
+        let c = eval(&constraint.c);
+
+        if a * &b != c {
+            return Err((i, a, b, c));
+        }
+    }
+
+    Ok(())
+}
+
 /// Enforce a zkInterface constraint in bellman CS.
 pub fn enforce<F: PrimeField, CS>(cs: &mut CS, vars: &HashMap<u64, Variable>, constraint: &Constraint)
     where 
@@ -51,11 +107,27 @@ pub fn enforce<F: PrimeField, CS>(cs: &mut CS, vars: &HashMap<u64, Variable>, co
     );
 }
 
+/// Allocates a fresh, disjoint zkInterface id range for each `call_gadget`
+/// invocation, so that nested or repeated foreign-gadget calls in the same
+/// circuit never collide on variable ids. Create one and thread it through
+/// every `call_gadget` call made while building a circuit.
+pub struct GadgetIdAllocator {
+    next_id: u64,
+}
+
+impl GadgetIdAllocator {
+    pub fn new() -> Self {
+        // Id 0 is reserved for the constant one.
+        GadgetIdAllocator { next_id: 1 }
+    }
+}
+
 /// Call a foreign gadget through zkInterface.
 pub fn call_gadget<F: PrimeField, CS: ConstraintSystem<F>>(
     cs: &mut CS,
     inputs: &[FpGadget<F>],
     exec_fn: & dyn Fn(&[u8]) -> Result<Messages, String>,
+    allocator: &mut GadgetIdAllocator,
 ) -> Result<(Vec<FpGadget<F>>), SynthesisError> {
     let witness_generation = inputs.len() > 0 && inputs[0].get_value().is_some();
 
@@ -63,15 +135,18 @@ pub fn call_gadget<F: PrimeField, CS: ConstraintSystem<F>>(
     let values = if witness_generation {
         let mut values = Vec::<u8>::new();
         for i in inputs {
-            i.get_value().unwrap().into_repr().write_le(&mut values)?;
+            i.get_value().unwrap().into_repr().write(&mut values)?;
         }
         Some(values)
     } else {
         None
     };
 
-    // Describe the input connections.
-    let first_input_id = 1;
+    // Describe the input connections in this call's own disjoint id range.
+    // `free_variable_id` tells the callee where its own ids must start, so
+    // everything it returns (outputs, private variables, constraint terms)
+    // already comes back in absolute, global ids — no remapping needed.
+    let first_input_id = allocator.next_id;
     let free_variable_id = first_input_id + inputs.len() as u64;
 
     let call = CircuitOwned {
@@ -81,7 +156,7 @@ pub fn call_gadget<F: PrimeField, CS: ConstraintSystem<F>>(
         },
         free_variable_id,
         r1cs_generation: true,
-        field_maximum: None,
+        field_maximum: Some(prime_field_maximum::<F>()),
     };
 
     // Prepare the call.
@@ -103,17 +178,22 @@ pub fn call_gadget<F: PrimeField, CS: ConstraintSystem<F>>(
     // Collect output variables and values to return.
     let mut outputs = Vec::new();
 
+    // The highest id this call has claimed so far, used to advance the
+    // allocator past everything this gadget invocation touched.
+    let mut max_id = free_variable_id - 1;
+
     // Allocate outputs, with optional values.
     if let Some(output_vars) = messages.connection_variables() {
         for var in output_vars {
             let num = FpGadget::alloc(
-                cs.ns(|| format!("output_{}", var.id)), 
+                cs.ns(|| format!("output_{}", var.id)),
                 || Ok(le_to_fr::<F>(var.value))
             )?;
 
             // Track output variable.
             id_to_var.insert(var.id, num.get_variable());
             outputs.push(num);
+            max_id = max_id.max(var.id);
         }
     }
 
@@ -124,12 +204,106 @@ pub fn call_gadget<F: PrimeField, CS: ConstraintSystem<F>>(
         let alloc_var = cs.alloc(|| format!("local_{}", var.id), || Ok(le_to_fr::<F>(var.value)))?;
         // Track private variable.
         id_to_var.insert(var.id, alloc_var);
+        max_id = max_id.max(var.id);
     };
 
-    // Add gadget constraints.
+    // Add gadget constraints. Ids are already absolute, relative to the
+    // `free_variable_id` we gave the callee, so no remapping is needed.
     for (i, constraint) in messages.iter_constraints().enumerate() {
         enforce(&mut cs.ns(|| format!("constraint_{}", i)), &id_to_var, &constraint);
     }
 
+    // Reserve this call's whole id range so the next call starts past it.
+    allocator.next_id = max_id + 1;
+
     Ok(outputs)
 }
+
+#[test]
+fn test_call_gadget_twice_without_id_collisions() {
+    use algebra_bls12_381::Bls12_381Fr as Fr;
+    use r1cs_core::ConstraintSynthesizer;
+    use zkinterface::writing::{ConstraintOwned, ConstraintSystemOwned};
+    use super::export::ExportingConstraintSystem;
+
+    /// A toy foreign gadget proving `output = input + 1`.
+    fn increment_gadget(call_buf: &[u8]) -> Result<Messages, String> {
+        let mut call = Messages::new(1);
+        call.read_buffer(call_buf).map_err(|e| format!("{:?}", e))?;
+
+        let circuit = call.last_circuit().ok_or("missing circuit")?;
+        let input = call.connection_variables().ok_or("missing input")?.remove(0);
+        let input_id = input.id;
+        let output_id = circuit.free_variable_id();
+
+        let input_value = le_to_fr::<Fr>(input.value);
+        let output_value = input_value + &Fr::one();
+
+        let mut buf = vec![];
+
+        CircuitOwned {
+            connections: VariablesOwned {
+                variable_ids: vec![output_id],
+                values: Some(fr_to_le(output_value)),
+            },
+            free_variable_id: output_id + 1,
+            r1cs_generation: true,
+            field_maximum: Some(prime_field_maximum::<Fr>()),
+        }.write(&mut buf).map_err(|e| format!("{:?}", e))?;
+
+        // `(one + input) * one = output`.
+        ConstraintSystemOwned {
+            constraints: vec![ConstraintOwned {
+                a: VariablesOwned {
+                    variable_ids: vec![0, input_id],
+                    values: Some([fr_to_le(Fr::one()), fr_to_le(Fr::one())].concat()),
+                },
+                b: VariablesOwned {
+                    variable_ids: vec![0],
+                    values: Some(fr_to_le(Fr::one())),
+                },
+                c: VariablesOwned {
+                    variable_ids: vec![output_id],
+                    values: Some(fr_to_le(Fr::one())),
+                },
+            }],
+        }.write(&mut buf).map_err(|e| format!("{:?}", e))?;
+
+        let mut messages = Messages::new(1);
+        messages.read_buffer(&buf).map_err(|e| format!("{:?}", e))?;
+        Ok(messages)
+    }
+
+    struct TwoIncrements {
+        x: Option<Fr>,
+    }
+
+    impl ConstraintSynthesizer<Fr> for TwoIncrements {
+        fn generate_constraints<CS: ConstraintSystem<Fr>>(self, cs: &mut CS) -> Result<(), SynthesisError> {
+            let x = FpGadget::alloc(cs.ns(|| "x"), || self.x.ok_or(SynthesisError::AssignmentMissing))?;
+
+            let mut allocator = GadgetIdAllocator::new();
+            let y = call_gadget(cs, &[x], &increment_gadget, &mut allocator)?;
+            // The first call claims ids 1 (input) and 2 (output).
+            assert_eq!(allocator.next_id, 3);
+            call_gadget(cs, &y, &increment_gadget, &mut allocator)?;
+            // The second call starts past them and claims ids 3 and 4.
+            assert_eq!(allocator.next_id, 5);
+
+            Ok(())
+        }
+    }
+
+    let circuit = TwoIncrements { x: Some(Fr::from(3u64)) };
+    let exported = ExportingConstraintSystem::collect(circuit).unwrap();
+
+    let mut buffer = vec![];
+    exported.write_to(&mut buffer).unwrap();
+
+    let mut messages = Messages::new(1);
+    messages.read_buffer(&buffer).unwrap();
+
+    // Both gadget calls' constraints must also hold against the collected
+    // witness.
+    check_witness::<Fr>(&messages).unwrap();
+}
@@ -5,15 +5,35 @@ use r1cs_core::{
     Variable,
 };
 use algebra::{Field, PairingEngine};
+use algebra::curves::bls12_381::Bls12;
 use rand::OsRng;
 use r1cs_std::circuit::num::AllocatedNum;
 use std::collections::HashMap;
 use std::fs::File;
 use std::path::Path;
-use super::import::{enforce, le_to_fr};
+use super::import::{check_witness, enforce, le_to_fr, prime_field_maximum};
+use groth16::{
+    create_random_proof,
+    generate_random_parameters,
+    prepare_verifying_key,
+    verify_proof,
+    Parameters,
+    Proof,
+};
 pub use zkinterface::reading::Messages;
 
 
+/// Compare two zkInterface little-endian byte encodings as the field values
+/// they represent, without decoding through `le_to_fr` (which zero-pads an
+/// undersized encoding but would silently truncate an oversized one).
+fn le_bytes_eq(a: &[u8], b: &[u8]) -> bool {
+    fn trimmed(bytes: &[u8]) -> &[u8] {
+        let len = bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        &bytes[..len]
+    }
+    trimmed(a) == trimmed(b)
+}
+
 /// A circuit instance built from zkif messages.
 #[derive(Clone, Debug)]
 pub struct ZKIFCircuit<'a> {
@@ -54,10 +74,22 @@ impl<'a, F: Field> ConstraintSynthesizer<F> for ZKIFCircuit<'a> {
 }
 
 
-/// Process a circuit.
-pub fn zkif_backend(
+/// Which phase of the setup/prove/verify pipeline to run. Selected by the
+/// caller (e.g. a CLI subcommand) so that, say, a `verify` request can never
+/// have the side effect of silently (re)running setup or proving.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Mode {
+    /// Run circuit setup (if requested) and witness/proof generation.
+    Prove,
+    /// Run only proof verification.
+    Verify,
+}
+
+/// Process a circuit against a chosen pairing-friendly curve.
+pub fn zkif_backend<E: PairingEngine>(
     messages: &Messages,
     out_dir: &Path,
+    mode: Mode,
 ) -> Result<(), SynthesisError>
 {
     let key_path = out_dir.join("key");
@@ -67,33 +99,81 @@ pub fn zkif_backend(
 
     let circuit_msg = messages.last_circuit().ok_or(SynthesisError::AssignmentMissing)?;
 
-    let mut rng = OsRng::new()?;
+    // Reject a circuit that was authored for a different field than `E::Fr`.
+    //
+    // Compared as trimmed bytes rather than through `le_to_fr`: that helper
+    // zero-pads an undersized encoding but silently truncates an oversized
+    // one, which would let a circuit built for a larger field slip through.
+    if let Some(field_maximum) = circuit_msg.field_maximum() {
+        if !le_bytes_eq(field_maximum, &prime_field_maximum::<E::Fr>()) {
+            return Err(SynthesisError::Unsatisfiable);
+        }
+    }
 
-    if circuit_msg.r1cs_generation() {
-        let params = generate_random_parameters::<Bls12, _, _>(
-            circuit.clone(),
-            &mut rng,
-        )?;
+    match mode {
+        Mode::Prove => {
+            let mut rng = OsRng::new()?;
+
+            if circuit_msg.r1cs_generation() {
+                let params = generate_random_parameters::<E, _, _>(
+                    circuit.clone(),
+                    &mut rng,
+                )?;
+
+                // Store params.
+                let f = File::create(&key_path)?;
+                params.write(f)?;
+            }
+
+            if circuit_msg.witness_generation() {
+                // Cheaply reject an unsatisfied witness before paying for proving.
+                if check_witness::<E::Fr>(messages).is_err() {
+                    return Err(SynthesisError::Unsatisfiable);
+                }
+
+                // Load params.
+                let mut fs = File::open(&key_path)?;
+                let params = Parameters::<E>::read(&mut fs, false)?;
+
+                let proof = create_random_proof(
+                    circuit,
+                    &params,
+                    &mut rng,
+                )?;
+
+                // Store proof.
+                let f = File::create(proof_path)?;
+                proof.write(f)?;
+            }
+        }
 
-        // Store params.
-        let f = File::create(&key_path)?;
-        params.write(f)?;
-    }
+        Mode::Verify => {
+            if !circuit_msg.verification() {
+                return Err(SynthesisError::Unsatisfiable);
+            }
 
-    if circuit_msg.witness_generation() {
-        // Load params.
-        let mut fs = File::open(&key_path)?;
-        let params = Parameters::<Bls12>::read(&mut fs, false)?;
+            // Load params.
+            let mut fs = File::open(&key_path)?;
+            let params = Parameters::<E>::read(&mut fs, false)?;
+            let pvk = prepare_verifying_key(&params.vk);
 
-        let proof = create_random_proof(
-            circuit,
-            &params,
-            &mut rng,
-        )?;
+            // Load proof.
+            let mut fs = File::open(&proof_path)?;
+            let proof = Proof::<E>::read(&mut fs)?;
 
-        // Store proof.
-        let f = File::create(proof_path)?;
-        proof.write(f)?;
+            // Public inputs are the connection variables of the circuit.
+            let public_vars = messages.connection_variables().unwrap();
+            let public_inputs: Vec<_> = public_vars
+                .iter()
+                .map(|var| le_to_fr::<E::Fr>(var.value))
+                .collect();
+
+            let verified = verify_proof(&pvk, &proof, &public_inputs)?;
+
+            if !verified {
+                return Err(SynthesisError::Unsatisfiable);
+            }
+        }
     }
     Ok(())
 }
@@ -111,7 +191,7 @@ fn test_zkif_backend() {
         messages.read_file(test_dir.join("r1cs.zkif")).unwrap();
         messages.read_file(test_dir.join("circuit_r1cs.zkif")).unwrap();
 
-        zkif_backend(&messages, out_dir).unwrap();
+        zkif_backend::<Bls12>(&messages, out_dir, Mode::Prove).unwrap();
     }
 
     // Prove.
@@ -120,6 +200,15 @@ fn test_zkif_backend() {
         messages.read_file(test_dir.join("witness.zkif")).unwrap();
         messages.read_file(test_dir.join("circuit_witness.zkif")).unwrap();
 
-        zkif_backend(&messages, out_dir).unwrap();
+        zkif_backend::<Bls12>(&messages, out_dir, Mode::Prove).unwrap();
+    }
+
+    // Verify.
+    {
+        let mut messages = Messages::new(1);
+        messages.read_file(test_dir.join("witness.zkif")).unwrap();
+        messages.read_file(test_dir.join("circuit_verification.zkif")).unwrap();
+
+        zkif_backend::<Bls12>(&messages, out_dir, Mode::Verify).unwrap();
     }
 }